@@ -1,6 +1,9 @@
 use std::borrow::Cow;
 
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
 
 use super::{dep_path::DepPath, LockfileVersion};
 
@@ -10,19 +13,32 @@ type Map<K, V> = std::collections::BTreeMap<K, V>;
 pub enum Error {
     #[error("yaml: {0}")]
     Yaml(#[from] serde_yaml::Error),
+    #[error("unable to parse integrity string: {0}")]
+    InvalidIntegrity(String),
+    #[error("no integrity recorded for {0}")]
+    MissingIntegrity(String),
+    #[error("integrity mismatch for {0}")]
+    IntegrityMismatch(String),
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PnpmLockfileData {
     lockfile_version: LockfileVersion,
+    #[serde(skip_serializing_if = "Option::is_none")]
     never_built_dependencies: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     only_built_dependencies: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     overrides: Option<Map<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     package_extensions_checksum: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     patched_dependencies: Option<Map<String, PatchFile>>,
     importers: Map<String, ProjectSnapshot>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     packages: Option<Map<String, PackageSnapshot>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     time: Option<Map<String, String>>,
 }
 
@@ -37,7 +53,9 @@ pub struct PatchFile {
 pub struct ProjectSnapshot {
     #[serde(flatten)]
     dependencies: DependencyInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
     dependencies_meta: Option<Map<String, DependenciesMeta>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     publish_directory: Option<String>,
 }
 
@@ -46,15 +64,22 @@ pub struct ProjectSnapshot {
 pub enum DependencyInfo {
     #[serde(rename_all = "camelCase")]
     PreV6 {
+        #[serde(skip_serializing_if = "Option::is_none")]
         specifiers: Option<Map<String, String>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
         dependencies: Option<Map<String, String>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
         optional_dependencies: Option<Map<String, String>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
         dev_dependencies: Option<Map<String, String>>,
     },
     #[serde(rename_all = "camelCase")]
     V6 {
+        #[serde(skip_serializing_if = "Option::is_none")]
         dependencies: Option<Map<String, Dependency>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
         optional_dependencies: Option<Map<String, Dependency>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
         dev_dependencies: Option<Map<String, Dependency>>,
     },
 }
@@ -70,13 +95,19 @@ pub struct Dependency {
 pub struct PackageSnapshot {
     // can we make this flow?/is it necessary?
     resolution: PackageResolution,
+    #[serde(skip_serializing_if = "Option::is_none")]
     id: Option<String>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
     name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     version: Option<String>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
     dependencies: Option<Map<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     optional_dependencies: Option<Map<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     patched: Option<bool>,
 
     #[serde(flatten)]
@@ -85,19 +116,27 @@ pub struct PackageSnapshot {
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct DependenciesMeta {
+    #[serde(skip_serializing_if = "Option::is_none")]
     injected: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     node: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     patch: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct PackageResolution {
-    #[serde(rename = "type")]
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
     type_field: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     integrity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     tarball: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     dir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     repo: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     commit: Option<String>,
 }
 
@@ -118,6 +157,54 @@ impl PnpmLockfileData {
         patches
     }
 
+    pub fn verify_integrity(&self, key: &str, tarball_bytes: &[u8]) -> Result<(), Error> {
+        let entry = self
+            .get_packages(key)
+            .ok_or_else(|| Error::MissingIntegrity(key.to_string()))?;
+        if matches!(
+            entry.resolution.type_field.as_deref(),
+            Some("git") | Some("directory")
+        ) {
+            return Ok(());
+        }
+        let integrity = entry
+            .resolution
+            .integrity
+            .as_deref()
+            .ok_or_else(|| Error::MissingIntegrity(key.to_string()))?;
+
+        let digests = parse_integrity(integrity)?;
+        let matches = digests
+            .iter()
+            .any(|(algo, expected)| constant_time_eq(&digest_tarball(*algo, tarball_bytes), expected));
+
+        if matches {
+            Ok(())
+        } else {
+            Err(Error::IntegrityMismatch(key.to_string()))
+        }
+    }
+
+    pub fn fixup_integrity(&mut self, cache: impl Fn(&str) -> Option<String>) {
+        let Some(packages) = self.packages.as_mut() else {
+            return;
+        };
+        for (key, entry) in packages.iter_mut() {
+            if entry.resolution.integrity.is_some() {
+                continue;
+            }
+            if matches!(
+                entry.resolution.type_field.as_deref(),
+                Some("git") | Some("directory")
+            ) {
+                continue;
+            }
+            if let Some(integrity) = cache(key) {
+                entry.resolution.integrity = Some(integrity);
+            }
+        }
+    }
+
     fn get_packages(&self, key: &str) -> Option<&PackageSnapshot> {
         self.packages
             .as_ref()
@@ -172,7 +259,7 @@ impl PnpmLockfileData {
         workspace_path: &str,
         name: &str,
         specifier: &'a str,
-    ) -> Result<Option<&'a str>, crate::Error> {
+    ) -> Result<Option<Cow<'a, str>>, crate::Error> {
         let importer = self.get_workspace(workspace_path)?;
 
         let Some((resolved_specifier, resolved_version)) = importer.dependencies.find_resolution(name) else {
@@ -182,17 +269,62 @@ impl PnpmLockfileData {
 
         let override_specifier = self.apply_overrides(name, specifier);
         if resolved_specifier == override_specifier {
-            Ok(Some(resolved_version))
+            Ok(Some(resolved_version.into()))
         } else if self
             .get_packages(&self.format_key(name, override_specifier))
             .is_some()
         {
-            Ok(Some(override_specifier))
+            Ok(Some(override_specifier.into()))
+        } else if let Some(version) = self.resolve_by_semver_range(name, override_specifier) {
+            Ok(Some(version))
         } else {
             Ok(None)
         }
     }
 
+    // Falls back to semver-range resolution when `specifier` isn't recorded
+    // verbatim in the importer: scans `packages` for the highest version of
+    // `name` satisfying `specifier` as a `VersionReq`, preferring a
+    // non-prerelease match unless the requested range itself mentions a
+    // prerelease. Non-semver specifiers (`workspace:*`, `link:`, `npm:`
+    // aliases, `github:` refs, ...) are ignored by bailing out when the
+    // specifier doesn't parse.
+    fn resolve_by_semver_range<'a>(&'a self, name: &str, specifier: &str) -> Option<Cow<'a, str>> {
+        let req = semver::VersionReq::parse(specifier).ok()?;
+        let requested_prerelease = specifier.contains('-');
+        let packages = self.packages.as_ref()?;
+
+        let mut best: Option<(semver::Version, Cow<'a, str>)> = None;
+        for key in packages.keys() {
+            let Ok(dp) = DepPath::try_from(key.as_str()) else {
+                continue;
+            };
+            if dp.name != name {
+                continue;
+            }
+            let Ok(version) = semver::Version::parse(dp.version) else {
+                continue;
+            };
+            if !req.matches(&version) {
+                continue;
+            }
+            if !version.pre.is_empty() && !requested_prerelease {
+                continue;
+            }
+            let Some(tail) = self.extract_version(key) else {
+                continue;
+            };
+            if best
+                .as_ref()
+                .map_or(true, |(best_version, _)| version > *best_version)
+            {
+                best = Some((version, tail));
+            }
+        }
+
+        best.map(|(_, tail)| tail)
+    }
+
     pub fn subgraph(
         &self,
         workspace_paths: &[String],
@@ -264,6 +396,246 @@ impl PnpmLockfileData {
             time: None,
         })
     }
+
+    pub fn plan_upgrades(&self, policy: UpgradePolicy) -> Vec<SpecifierUpgrade> {
+        let mut upgrades = Vec::new();
+        for (workspace, importer) in &self.importers {
+            for (name, specifier) in importer.dependencies.all_specifiers() {
+                if specifier.starts_with("workspace:")
+                    || specifier.starts_with("link:")
+                    || specifier.starts_with("npm:")
+                {
+                    continue;
+                }
+                let Some((_, resolved_version)) = importer.dependencies.find_resolution(name)
+                else {
+                    continue;
+                };
+                let Ok(req) = semver::VersionReq::parse(specifier) else {
+                    continue;
+                };
+                let versions = self.available_versions(name);
+                if versions.is_empty() {
+                    continue;
+                }
+
+                let new_specifier = match policy {
+                    UpgradePolicy::Compatible => {
+                        let current = parse_loose_version(resolved_version);
+                        versions
+                            .iter()
+                            .filter(|version| req.matches(version))
+                            .filter(|version| {
+                                current.as_ref().map_or(true, |current| *version > current)
+                            })
+                            .max()
+                            .and_then(|version| format_upgraded_specifier(specifier, version))
+                    }
+                    UpgradePolicy::Latest => versions
+                        .iter()
+                        .max()
+                        .map(|version| format!("^{}.{}.{}", version.major, version.minor, version.patch)),
+                };
+
+                if let Some(new_specifier) = new_specifier {
+                    if new_specifier != specifier {
+                        upgrades.push(SpecifierUpgrade {
+                            workspace: workspace.clone(),
+                            name: name.to_string(),
+                            old_specifier: specifier.to_string(),
+                            new_specifier,
+                        });
+                    }
+                }
+            }
+        }
+        upgrades
+    }
+
+    fn available_versions(&self, name: &str) -> Vec<semver::Version> {
+        let Some(packages) = self.packages.as_ref() else {
+            return Vec::new();
+        };
+        let mut versions = packages
+            .keys()
+            .filter_map(|key| {
+                let dp = DepPath::try_from(key.as_str()).ok()?;
+                if dp.name != name {
+                    return None;
+                }
+                semver::Version::parse(dp.version).ok()
+            })
+            .collect::<Vec<_>>();
+        versions.sort();
+        versions
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SpecifierUpgrade {
+    pub workspace: String,
+    pub name: String,
+    pub old_specifier: String,
+    pub new_specifier: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpgradePolicy {
+    Compatible,
+    Latest,
+}
+
+// Reapplies the range operator (`^`, `~`, `>=`, ...) `original` started with
+// to `version`, so an upgrade to a `^`-range specifier stays a `^`-range
+// instead of collapsing to a bare, hard-pinned version. Bails out (leaving
+// the specifier untouched) for compound requirements such as
+// ">=1.2.3, <1.8.0" — `VersionReq::parse` accepts a comma-separated list of
+// comparators, and rewriting just the first one would silently drop the
+// rest of the range.
+fn format_upgraded_specifier(original: &str, version: &semver::Version) -> Option<String> {
+    if original.contains(',') {
+        return None;
+    }
+    let prefix_len = original.find(|c: char| c.is_ascii_digit()).unwrap_or(0);
+    Some(format!("{}{version}", &original[..prefix_len]))
+}
+
+// Drops a pnpm peer/patch suffix (e.g. the `_hash` in
+// `12.2.5_ir3quccc6i62x6qn6jjhyjjiey`, or the `(patch_hash=...)` in
+// `4.17.21(patch_hash=lgum37zgng4nfkynzh3cs7wdeq)`) before parsing.
+fn parse_loose_version(version: &str) -> Option<semver::Version> {
+    let end = version.find(['_', '(']).unwrap_or(version.len());
+    semver::Version::parse(&version[..end]).ok()
+}
+
+impl PnpmLockfileData {
+    pub fn diff(&self, other: &Self) -> LockfileDiff {
+        let empty = Map::new();
+        let self_packages = self.packages.as_ref().unwrap_or(&empty);
+        let other_packages = other.packages.as_ref().unwrap_or(&empty);
+
+        let mut added_packages = other_packages
+            .keys()
+            .filter(|key| !self_packages.contains_key(*key))
+            .cloned()
+            .collect::<Vec<_>>();
+        added_packages.sort();
+
+        let mut removed_packages = self_packages
+            .keys()
+            .filter(|key| !other_packages.contains_key(*key))
+            .cloned()
+            .collect::<Vec<_>>();
+        removed_packages.sort();
+
+        let mut changed_packages = Vec::new();
+        for (key, old) in self_packages {
+            let Some(new) = other_packages.get(key) else {
+                continue;
+            };
+            if old == new {
+                continue;
+            }
+
+            // Neutralize the fields already surfaced as dedicated flags,
+            // then compare what's left so callers aren't told "nothing
+            // changed" for packages that differ only in e.g. `patched` or
+            // an `other` catch-all field.
+            let mut old_without_tracked_fields = old.clone();
+            old_without_tracked_fields.version = new.version.clone();
+            old_without_tracked_fields.resolution.integrity = new.resolution.integrity.clone();
+            old_without_tracked_fields.dependencies = new.dependencies.clone();
+            old_without_tracked_fields.optional_dependencies = new.optional_dependencies.clone();
+
+            changed_packages.push(PackageChange {
+                key: key.clone(),
+                old_version: old.version.clone(),
+                new_version: new.version.clone(),
+                integrity_changed: old.resolution.integrity != new.resolution.integrity,
+                dependencies_changed: old.dependencies != new.dependencies
+                    || old.optional_dependencies != new.optional_dependencies,
+                other_fields_changed: old_without_tracked_fields != *new,
+            });
+        }
+
+        let mut workspaces = self.importers.keys().collect::<Vec<_>>();
+        workspaces.extend(other.importers.keys());
+        workspaces.sort();
+        workspaces.dedup();
+
+        let mut importer_changes = Vec::new();
+        for workspace in workspaces {
+            let old_importer = self.importers.get(workspace);
+            let new_importer = other.importers.get(workspace);
+
+            let mut names = old_importer
+                .map(|importer| importer.dependencies.all_specifiers())
+                .unwrap_or_default();
+            names.extend(
+                new_importer
+                    .map(|importer| importer.dependencies.all_specifiers())
+                    .unwrap_or_default(),
+            );
+            let mut names = names.into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+            names.sort_unstable();
+            names.dedup();
+
+            for name in names {
+                let old_resolution = old_importer.and_then(|importer| importer.dependencies.find_resolution(name));
+                let new_resolution = new_importer.and_then(|importer| importer.dependencies.find_resolution(name));
+                if old_resolution == new_resolution {
+                    continue;
+                }
+                importer_changes.push(ImporterChange {
+                    workspace: workspace.clone(),
+                    name: name.to_string(),
+                    old_specifier: old_resolution.map(|(specifier, _)| specifier.to_string()),
+                    new_specifier: new_resolution.map(|(specifier, _)| specifier.to_string()),
+                    old_version: old_resolution.map(|(_, version)| version.to_string()),
+                    new_version: new_resolution.map(|(_, version)| version.to_string()),
+                });
+            }
+        }
+
+        LockfileDiff {
+            added_packages,
+            removed_packages,
+            changed_packages,
+            importer_changes,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LockfileDiff {
+    pub added_packages: Vec<String>,
+    pub removed_packages: Vec<String>,
+    pub changed_packages: Vec<PackageChange>,
+    pub importer_changes: Vec<ImporterChange>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageChange {
+    pub key: String,
+    pub old_version: Option<String>,
+    pub new_version: Option<String>,
+    pub integrity_changed: bool,
+    pub dependencies_changed: bool,
+    pub other_fields_changed: bool,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ImporterChange {
+    pub workspace: String,
+    pub name: String,
+    pub old_specifier: Option<String>,
+    pub new_specifier: Option<String>,
+    pub old_version: Option<String>,
+    pub new_version: Option<String>,
 }
 
 impl crate::Lockfile for PnpmLockfileData {
@@ -286,6 +658,7 @@ impl crate::Lockfile for PnpmLockfileData {
         let Some(resolved_version) = self.resolve_specifier(workspace_path, name, version)? else {
             return Ok(None)
         };
+        let resolved_version = resolved_version.as_ref();
 
         let key = self.format_key(name, resolved_version);
 
@@ -328,6 +701,47 @@ impl crate::Lockfile for PnpmLockfileData {
                 .collect(),
         ))
     }
+
+    fn to_bytes(&self) -> Result<Vec<u8>, crate::Error> {
+        let mut normalized = self.clone();
+        // `time` isn't meaningful for a pruned/regenerated lockfile and
+        // pnpm itself omits it when the info isn't available.
+        normalized.time = None;
+        for package in normalized.packages.iter_mut().flatten().values_mut() {
+            package.other = package
+                .other
+                .iter()
+                .map(|(key, value)| (key.clone(), normalize_yaml(value.clone())))
+                .collect();
+        }
+
+        let yaml = serde_yaml::to_string(&normalized).map_err(Error::from)?;
+        Ok(yaml.into_bytes())
+    }
+}
+
+/// Recursively sorts YAML mapping keys so that re-serializing the same
+/// logical document always produces byte-identical output, even when the
+/// original ordering came from an untyped `other` catch-all map.
+fn normalize_yaml(value: serde_yaml::Value) -> serde_yaml::Value {
+    match value {
+        serde_yaml::Value::Mapping(mapping) => {
+            let mut entries = mapping
+                .into_iter()
+                .map(|(key, value)| (key, normalize_yaml(value)))
+                .collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| {
+                let a = serde_yaml::to_string(a).unwrap_or_default();
+                let b = serde_yaml::to_string(b).unwrap_or_default();
+                a.cmp(&b)
+            });
+            serde_yaml::Value::Mapping(entries.into_iter().collect())
+        }
+        serde_yaml::Value::Sequence(sequence) => {
+            serde_yaml::Value::Sequence(sequence.into_iter().map(normalize_yaml).collect())
+        }
+        other => other,
+    }
 }
 
 impl DependencyInfo {
@@ -359,6 +773,29 @@ impl DependencyInfo {
     fn get_resolution<'a, V>(maybe_map: &'a Option<Map<String, V>>, key: &str) -> Option<&'a V> {
         maybe_map.as_ref().and_then(|maybe_map| maybe_map.get(key))
     }
+
+    /// Lists every dependency name paired with its recorded specifier,
+    /// across the regular/dev/optional dependency maps.
+    fn all_specifiers(&self) -> Vec<(&str, &str)> {
+        match self {
+            DependencyInfo::PreV6 { specifiers, .. } => specifiers
+                .iter()
+                .flatten()
+                .map(|(name, specifier)| (name.as_str(), specifier.as_str()))
+                .collect(),
+            DependencyInfo::V6 {
+                dependencies,
+                optional_dependencies,
+                dev_dependencies,
+            } => dependencies
+                .iter()
+                .flatten()
+                .chain(dev_dependencies.iter().flatten())
+                .chain(optional_dependencies.iter().flatten())
+                .map(|(name, dep)| (name.as_str(), dep.specifier.as_str()))
+                .collect(),
+        }
+    }
 }
 
 impl Dependency {
@@ -368,8 +805,59 @@ impl Dependency {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IntegrityAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl IntegrityAlgorithm {
+    fn parse(algo: &str) -> Option<Self> {
+        match algo {
+            "sha1" => Some(Self::Sha1),
+            "sha256" => Some(Self::Sha256),
+            "sha512" => Some(Self::Sha512),
+            _ => None,
+        }
+    }
+}
+
+fn parse_integrity(integrity: &str) -> Result<Vec<(IntegrityAlgorithm, Vec<u8>)>, Error> {
+    integrity
+        .split_whitespace()
+        .map(|entry| {
+            let (algo, digest) = entry
+                .split_once('-')
+                .ok_or_else(|| Error::InvalidIntegrity(entry.to_string()))?;
+            let algorithm = IntegrityAlgorithm::parse(algo)
+                .ok_or_else(|| Error::InvalidIntegrity(entry.to_string()))?;
+            let bytes = BASE64
+                .decode(digest)
+                .map_err(|_| Error::InvalidIntegrity(entry.to_string()))?;
+            Ok((algorithm, bytes))
+        })
+        .collect()
+}
+
+fn digest_tarball(algorithm: IntegrityAlgorithm, bytes: &[u8]) -> Vec<u8> {
+    match algorithm {
+        IntegrityAlgorithm::Sha1 => Sha1::digest(bytes).to_vec(),
+        IntegrityAlgorithm::Sha256 => Sha256::digest(bytes).to_vec(),
+        IntegrityAlgorithm::Sha512 => Sha512::digest(bytes).to_vec(),
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
 #[cfg(test)]
 mod tests {
+    use base64::Engine;
     use pretty_assertions::assert_eq;
     use test_case::test_case;
 
@@ -400,6 +888,124 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_to_bytes_is_stable() {
+        for fixture in &[PNPM6, PNPM7, PNPM8] {
+            let lockfile = PnpmLockfileData::from_bytes(fixture).unwrap();
+            let bytes = lockfile.to_bytes().unwrap();
+            let lockfile_from_bytes = PnpmLockfileData::from_bytes(&bytes).unwrap();
+            assert_eq!(lockfile, lockfile_from_bytes);
+            assert_eq!(bytes, lockfile_from_bytes.to_bytes().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_to_bytes_omits_null_fields() {
+        for fixture in &[PNPM6, PNPM7, PNPM8] {
+            let lockfile = PnpmLockfileData::from_bytes(fixture).unwrap();
+            let bytes = lockfile.to_bytes().unwrap();
+            let yaml = String::from_utf8(bytes).unwrap();
+            // pnpm never writes `key: null` for an absent field; it omits the
+            // key entirely, so our output shouldn't either.
+            assert!(!yaml.contains("null"), "unexpected null in: {yaml}");
+        }
+    }
+
+    #[test]
+    fn test_verify_integrity() {
+        let tarball = b"tarball contents";
+        let digest = BASE64.encode(Sha512::digest(tarball));
+        let yaml = format!(
+            "lockfileVersion: 5.4\n\
+             importers:\n  .: {{}}\n\
+             packages:\n\
+             \x20 /foo/1.0.0:\n\
+             \x20   resolution:\n\
+             \x20     integrity: sha512-{digest}\n\
+             \x20 /bar/1.0.0:\n\
+             \x20   resolution: {{}}\n\
+             \x20 /baz/1.0.0:\n\
+             \x20   resolution:\n\
+             \x20     type: git\n\
+             \x20     commit: deadbeef\n"
+        );
+        let lockfile = PnpmLockfileData::from_bytes(yaml.as_bytes()).unwrap();
+
+        assert!(lockfile.verify_integrity("/foo/1.0.0", tarball).is_ok());
+        assert!(lockfile.verify_integrity("/foo/1.0.0", b"wrong bytes").is_err());
+        // git packages use `commit`, not `integrity`, and are always accepted.
+        assert!(lockfile.verify_integrity("/baz/1.0.0", tarball).is_ok());
+        // missing integrity is an error, not a silent pass.
+        assert!(lockfile.verify_integrity("/bar/1.0.0", tarball).is_err());
+        assert!(lockfile.verify_integrity("/missing/1.0.0", tarball).is_err());
+    }
+
+    #[test]
+    fn test_fixup_integrity() {
+        let yaml = "lockfileVersion: 5.4\n\
+                    importers:\n  .: {}\n\
+                    packages:\n\
+                    \x20 /foo/1.0.0:\n\
+                    \x20   resolution: {}\n\
+                    \x20 /baz/1.0.0:\n\
+                    \x20   resolution:\n\
+                    \x20     type: git\n\
+                    \x20     commit: deadbeef\n";
+        let mut lockfile = PnpmLockfileData::from_bytes(yaml.as_bytes()).unwrap();
+
+        lockfile.fixup_integrity(|key| match key {
+            "/foo/1.0.0" => Some("sha512-deadbeef".to_string()),
+            _ => None,
+        });
+
+        assert_eq!(
+            lockfile
+                .get_packages("/foo/1.0.0")
+                .unwrap()
+                .resolution
+                .integrity
+                .as_deref(),
+            Some("sha512-deadbeef")
+        );
+        // git packages are skipped even though they lack `integrity`.
+        assert_eq!(
+            lockfile.get_packages("/baz/1.0.0").unwrap().resolution.integrity,
+            None
+        );
+    }
+
+    #[test_case("^4.0.0", "4.8.3", Some("^4.8.3") ; "caret prefix preserved")]
+    #[test_case("~4.0.0", "4.8.3", Some("~4.8.3") ; "tilde prefix preserved")]
+    #[test_case(">=4.0.0", "4.8.3", Some(">=4.8.3") ; "comparator prefix preserved")]
+    #[test_case("4.0.0", "4.8.3", Some("4.8.3") ; "exact pin preserved")]
+    #[test_case(">=1.2.3, <1.8.0", "1.7.9", None ; "compound requirement left untouched")]
+    fn test_format_upgraded_specifier(original: &str, version: &str, expected: Option<&str>) {
+        let version = semver::Version::parse(version).unwrap();
+        assert_eq!(
+            format_upgraded_specifier(original, &version),
+            expected.map(String::from)
+        );
+    }
+
+    #[test]
+    fn test_plan_upgrades_skips_workspace_specifiers_and_up_to_date_deps() {
+        let lockfile = PnpmLockfileData::from_bytes(PNPM7).unwrap();
+        let upgrades = lockfile.plan_upgrades(UpgradePolicy::Compatible);
+        // typescript is already resolved to the newest version satisfying its
+        // own range, so there's nothing to propose.
+        assert!(!upgrades
+            .iter()
+            .any(|u| u.workspace == "apps/web" && u.name == "typescript"));
+
+        let lockfile = PnpmLockfileData::from_bytes(PNPM8).unwrap();
+        let upgrades = lockfile.plan_upgrades(UpgradePolicy::Latest);
+        // "c" is a `workspace:*` dependency and must never be proposed for
+        // upgrade.
+        assert!(!upgrades
+            .iter()
+            .any(|u| u.workspace == "packages/a" && u.name == "c"));
+    }
+
     #[test]
     fn test_patches() {
         let lockfile =
@@ -438,6 +1044,22 @@ mod tests {
         Ok(Some("4.8.3"))
         ; "no peer deps"
     )]
+    #[test_case(
+        PNPM7,
+        "apps/web",
+        "typescript",
+        "^4.5.0",
+        Ok(Some("4.8.3"))
+        ; "semver range fallback for compatible but distinct specifier"
+    )]
+    #[test_case(
+        PNPM7,
+        "apps/web",
+        "typescript",
+        "^5.0.0",
+        Ok(None)
+        ; "semver range fallback rejects a range no package version satisfies"
+    )]
     #[test_case(
         PNPM7,
         "apps/web",
@@ -521,7 +1143,7 @@ mod tests {
 
         let actual = lockfile.resolve_specifier(workspace_path, package, specifier);
         match (actual, expected) {
-            (Ok(actual), Ok(expected)) => assert_eq!(actual, expected),
+            (Ok(actual), Ok(expected)) => assert_eq!(actual.as_deref(), expected),
             (Err(actual), Err(expected_msg)) => assert!(
                 actual.to_string().contains(expected_msg),
                 "Expected '{}' to appear in error message: '{}'",
@@ -534,6 +1156,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_resolve_by_semver_range_prefers_highest_match_in_packages() {
+        // "foo" is currently resolved to 1.0.0 for this workspace, but the
+        // lockfile also carries 1.5.0 and 2.0.0 elsewhere in `packages`
+        // (e.g. pulled in by another workspace). Requesting a range that the
+        // pinned 1.0.0 doesn't satisfy should find 1.5.0 by scanning
+        // `packages`, not just bail out because the workspace's own
+        // resolution doesn't match.
+        let yaml = "lockfileVersion: 5.4\n\
+                    importers:\n\
+                    \x20 apps/web:\n\
+                    \x20   specifiers:\n\
+                    \x20     foo: ^1.0.0\n\
+                    \x20   dependencies:\n\
+                    \x20     foo: 1.0.0\n\
+                    packages:\n\
+                    \x20 /foo/1.0.0:\n\
+                    \x20   resolution: {}\n\
+                    \x20 /foo/1.5.0:\n\
+                    \x20   resolution: {}\n\
+                    \x20 /foo/2.0.0:\n\
+                    \x20   resolution: {}\n";
+        let lockfile = PnpmLockfileData::from_bytes(yaml.as_bytes()).unwrap();
+
+        let resolved = lockfile
+            .resolve_specifier("apps/web", "foo", "^1.5.0")
+            .unwrap();
+        assert_eq!(resolved.as_deref(), Some("1.5.0"));
+    }
+
     #[test_case(
         PNPM7,
         "apps/docs",
@@ -648,6 +1300,67 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_diff_against_pruned_subgraph() {
+        let lockfile = PnpmLockfileData::from_bytes(PNPM_PATCH).unwrap();
+        let pruned = lockfile
+            .subgraph(
+                &["packages/dependency".into()],
+                &[
+                    "/is-odd/3.0.1_nrrwwz7lemethtlvvm75r5bmhq".into(),
+                    "/is-number/6.0.0".into(),
+                ],
+            )
+            .unwrap();
+
+        let diff = lockfile.diff(&pruned);
+
+        assert!(diff.added_packages.is_empty());
+        assert!(diff.changed_packages.is_empty());
+        assert!(diff
+            .removed_packages
+            .contains(&"/@babel/core/7.20.12_3hyn7hbvzkemudbydlwjmrb65y".to_string()));
+        assert!(diff
+            .removed_packages
+            .contains(&"/moleculer/0.14.28_5pk7ojv7qbqha75ozglk4y4f74_kumip57h7zlinbhp4gz3jrbqry"
+                .to_string()));
+        assert!(!diff
+            .removed_packages
+            .contains(&"/is-odd/3.0.1_nrrwwz7lemethtlvvm75r5bmhq".to_string()));
+
+        // diffing a lockfile against itself is a no-op.
+        assert_eq!(lockfile.diff(&lockfile), LockfileDiff::default());
+    }
+
+    #[test]
+    fn test_diff_flags_changes_outside_tracked_fields() {
+        let base_yaml = "lockfileVersion: 5.4\n\
+                          importers:\n  .: {}\n\
+                          packages:\n\
+                          \x20 /foo/1.0.0:\n\
+                          \x20   resolution: {}\n\
+                          \x20   patched: false\n";
+        let patched_yaml = "lockfileVersion: 5.4\n\
+                             importers:\n  .: {}\n\
+                             packages:\n\
+                             \x20 /foo/1.0.0:\n\
+                             \x20   resolution: {}\n\
+                             \x20   patched: true\n";
+        let before = PnpmLockfileData::from_bytes(base_yaml.as_bytes()).unwrap();
+        let after = PnpmLockfileData::from_bytes(patched_yaml.as_bytes()).unwrap();
+
+        let diff = before.diff(&after);
+
+        let change = diff
+            .changed_packages
+            .iter()
+            .find(|change| change.key == "/foo/1.0.0")
+            .expect("/foo/1.0.0 should be reported as changed");
+        assert!(!change.integrity_changed);
+        assert!(!change.dependencies_changed);
+        assert!(change.other_fields_changed);
+    }
+
     #[test]
     fn test_prune_patches_v6() {
         let lockfile = PnpmLockfileData::from_bytes(PNPM_PATCH_V6).unwrap();